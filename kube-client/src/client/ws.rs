@@ -0,0 +1,508 @@
+//! WebSocket support for `exec`, `attach`, and `port-forward` connections.
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes};
+use futures::{Sink, SinkExt, StreamExt};
+use h2::client::SendRequest;
+use http::{header, Method, Request, Response};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        protocol::{frame::coding::CloseCode as TungsteniteCloseCode, CloseFrame},
+        Error as TungsteniteError, Message,
+    },
+    WebSocketStream,
+};
+
+use crate::error::{CloseCode, CloseReason, WsError};
+
+/// Configuration for a WebSocket connection used by `exec`, `attach`, and `port-forward`.
+///
+/// By default neither keepalive nor redirect-following is enabled, matching the previous,
+/// unconfigurable behaviour.
+#[derive(Debug, Default, Clone)]
+pub struct WsConfig {
+    pub(crate) keep_alive_interval: Option<Duration>,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) max_redirects: Option<u8>,
+}
+
+impl WsConfig {
+    /// Send a Ping frame at this interval to keep the connection alive behind load balancers
+    /// and proxies that drop idle connections.
+    #[must_use]
+    pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Consider the connection dead, surfacing [`WsError::KeepAliveTimeout`], if no Pong
+    /// answering a given Ping is received within this duration.
+    ///
+    /// Only takes effect when [`keep_alive_interval`](Self::keep_alive_interval) is also set.
+    #[must_use]
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Follow HTTP `3XX` redirects during the WebSocket handshake, up to `max_hops` times.
+    ///
+    /// Auth headers are only preserved when the `Location` target is same-origin as the
+    /// original request. Exceeding `max_hops` surfaces [`WsError::TooManyRedirects`].
+    ///
+    /// Redirects are not followed unless this is set.
+    #[must_use]
+    pub fn max_redirects(mut self, max_hops: u8) -> Self {
+        self.max_redirects = Some(max_hops);
+        self
+    }
+}
+
+/// The `:protocol` pseudo-header value used to negotiate a WebSocket stream over an HTTP/2
+/// Extended CONNECT request (RFC 8441 §4).
+const WEBSOCKET_PROTOCOL: &str = "websocket";
+
+/// A bidirectional byte stream backed by a single HTTP/2 stream opened via Extended CONNECT.
+///
+/// Implements [`AsyncRead`]/[`AsyncWrite`] so it can be wrapped in the same
+/// [`WebSocketStream`] used for the HTTP/1.1 upgrade path, letting both transports share one
+/// `WebSocket` abstraction.
+pub(crate) struct H2Stream {
+    send: h2::SendStream<Bytes>,
+    recv: h2::RecvStream,
+    buf: Bytes,
+}
+
+impl AsyncRead for H2Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.buf.is_empty() {
+            match futures::ready!(Pin::new(&mut this.recv).poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = this.recv.flow_control().release_capacity(data.len());
+                    this.buf = data;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::Other, e))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let n = std::cmp::min(buf.remaining(), this.buf.len());
+        buf.put_slice(&this.buf[..n]);
+        this.buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for H2Stream {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        this.send
+            .send_data(Bytes::copy_from_slice(buf), false)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        this.send
+            .send_data(Bytes::new(), true)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Open a WebSocket stream over HTTP/2 using the RFC 8441 Extended CONNECT method.
+///
+/// `extended_connect_enabled` should reflect whether the peer's HTTP/2 SETTINGS advertised
+/// `SETTINGS_ENABLE_CONNECT_PROTOCOL`; when it hasn't,
+/// [`WsError::ExtendedConnectNotSupported`] is returned without attempting the handshake.
+/// A response status other than `200 OK` is surfaced as [`WsError::Http2ProtocolSwitch`].
+///
+/// Returns the same [`WebSocketStream`] type the HTTP/1.1 upgrade path produces, just
+/// parameterized over [`H2Stream`] instead of an HTTP/1.1 upgraded connection, so exec/attach/
+/// port-forward can drive either transport identically from here on.
+pub(crate) async fn connect_extended(
+    mut send_request: SendRequest<Bytes>,
+    mut request: Request<()>,
+    extended_connect_enabled: bool,
+) -> Result<WebSocketStream<H2Stream>, WsError> {
+    if !extended_connect_enabled {
+        return Err(WsError::ExtendedConnectNotSupported);
+    }
+
+    *request.method_mut() = Method::CONNECT;
+    request
+        .extensions_mut()
+        .insert(h2::ext::Protocol::from(WEBSOCKET_PROTOCOL));
+
+    let (response, send) = send_request.send_request(request, false).map_err(WsError::Http2)?;
+    let response: Response<h2::RecvStream> = response.await.map_err(WsError::Http2)?;
+
+    if response.status() != http::StatusCode::OK {
+        return Err(WsError::Http2ProtocolSwitch(response.status()));
+    }
+
+    let io = H2Stream {
+        send,
+        recv: response.into_body(),
+        buf: Bytes::new(),
+    };
+    Ok(WebSocketStream::from_raw_socket(io, tokio_tungstenite::tungstenite::protocol::Role::Client, None).await)
+}
+
+/// Converts the close frame a WebSocket peer sends when ending the connection into the
+/// [`CloseReason`] [`next_event`] yields on graceful termination.
+fn close_reason_from_frame(frame: Option<CloseFrame<'_>>) -> Option<CloseReason> {
+    frame.map(|frame| CloseReason {
+        code: CloseCode::from(u16::from(frame.code)),
+        description: if frame.reason.is_empty() {
+            None
+        } else {
+            Some(frame.reason.into_owned())
+        },
+    })
+}
+
+/// An event read from an exec/attach/port-forward WebSocket connection.
+#[derive(Debug)]
+pub(crate) enum WsEvent {
+    /// A data or control frame from the peer, to be handled by the caller.
+    Message(Message),
+    /// The peer closed the connection gracefully; carries its close reason, if any.
+    Closed(Option<CloseReason>),
+}
+
+/// Read the next event from a WebSocket connection used for exec, attach, or port-forward.
+///
+/// Yields [`WsEvent::Closed`] with the peer's [`CloseReason`] on graceful termination instead
+/// of mapping it to an [`Error`](crate::Error), so callers can distinguish a clean pod exit
+/// from a protocol-level failure.
+pub(crate) async fn next_event<S>(stream: &mut WebSocketStream<S>) -> Result<WsEvent, WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match stream.next().await {
+        Some(Ok(Message::Close(frame))) => Ok(WsEvent::Closed(close_reason_from_frame(frame))),
+        Some(Ok(message)) => Ok(WsEvent::Message(message)),
+        None | Some(Err(TungsteniteError::ConnectionClosed)) => Ok(WsEvent::Closed(None)),
+        Some(Err(TungsteniteError::AlreadyClosed)) => Err(WsError::AlreadyClosed),
+        Some(Err(TungsteniteError::Protocol(e))) => Err(WsError::ProtocolError(e)),
+        Some(Err(TungsteniteError::Capacity(e))) => Err(WsError::Capacity(e)),
+        Some(Err(_)) => Err(WsError::ConnectionClosed),
+    }
+}
+
+/// Send a close frame with a caller-chosen code and reason, e.g. to end an exec/attach/
+/// port-forward session deliberately rather than waiting for the peer to hang up.
+pub(crate) async fn send_close<S>(
+    stream: &mut WebSocketStream<S>,
+    code: CloseCode,
+    reason: impl Into<std::borrow::Cow<'static, str>>,
+) -> Result<(), WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream
+        .close(Some(CloseFrame {
+            code: TungsteniteCloseCode::from(u16::from(code)),
+            reason: reason.into(),
+        }))
+        .await
+        .map_err(|_| WsError::AlreadyClosed)
+}
+
+/// Drives Ping/Pong keepalive for a WebSocket connection according to `config`.
+///
+/// Runs alongside the connection's read loop: sends a `Ping` carrying a monotonic nonce on
+/// [`WsConfig::keep_alive_interval`], and relies on `pong_rx` being fed each `Pong` payload the
+/// read loop observes from the peer (automatic `Pong` replies to peer `Ping`s are handled by
+/// `tungstenite` itself, not here). A `Pong` is only accepted if its payload matches the most
+/// recently sent `Ping`'s nonce; stale `Pong`s left over from an earlier round are drained and
+/// ignored so they can't be mistaken for an answer to the current `Ping`. Resolves with
+/// [`WsError::KeepAliveTimeout`] if [`WsConfig::idle_timeout`] elapses before a matching
+/// `Pong` arrives. Returns immediately if no `keep_alive_interval` is configured.
+pub(crate) async fn keep_alive<Si>(
+    mut sink: Si,
+    mut pong_rx: mpsc::Receiver<Vec<u8>>,
+    config: WsConfig,
+) -> Result<(), WsError>
+where
+    Si: Sink<Message> + Unpin,
+{
+    let Some(interval) = config.keep_alive_interval else {
+        return Ok(());
+    };
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await; // first tick fires immediately; skip it so the first Ping waits a full interval
+    let mut nonce: u64 = 0;
+
+    loop {
+        ticker.tick().await;
+        nonce = nonce.wrapping_add(1);
+        let payload = nonce.to_be_bytes().to_vec();
+        sink.send(Message::Ping(payload.clone()))
+            .await
+            .map_err(|_| WsError::ConnectionClosed)?;
+
+        let Some(idle_timeout) = config.idle_timeout else {
+            continue;
+        };
+        let deadline = tokio::time::Instant::now() + idle_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            match tokio::time::timeout(remaining, pong_rx.recv()).await {
+                Ok(Some(pong)) if pong == payload => break,
+                Ok(Some(_stale)) => continue,
+                Ok(None) => return Err(WsError::ConnectionClosed),
+                Err(_) => return Err(WsError::KeepAliveTimeout),
+            }
+        }
+    }
+}
+
+/// Resolves a `Location` header value against the URI of the request that produced it.
+///
+/// Redirect targets are frequently relative (path-only); `location` is used as-is when it
+/// already carries a scheme and authority, and otherwise has `base`'s scheme and authority
+/// grafted on.
+fn resolve_location(base: &http::Uri, location: &str) -> Result<http::Uri, WsError> {
+    let location: http::Uri = location.parse().map_err(|_| WsError::TooManyRedirects)?;
+    if location.scheme().is_some() && location.authority().is_some() {
+        return Ok(location);
+    }
+
+    let mut parts = location.into_parts();
+    parts.scheme = base.scheme().cloned();
+    parts.authority = base.authority().cloned();
+    http::Uri::from_parts(parts).map_err(|_| WsError::TooManyRedirects)
+}
+
+/// Follow HTTP `3XX` redirects while performing a WebSocket handshake.
+///
+/// `send_handshake` performs a single upgrade attempt against the given request and returns
+/// its response. On a `3XX` response, this re-issues the handshake against the redirect's
+/// `Location` (resolved against the current request's URI when relative), dropping
+/// `Authorization`/`Cookie` headers unless the target is same-origin (same scheme and
+/// authority) as the request that produced the redirect, up to `config.max_redirects` hops.
+/// Returns [`WsError::TooManyRedirects`] if the hop limit is exceeded before a non-redirect
+/// response is produced.
+///
+/// Redirects are not followed at all, and `send_handshake`'s response is returned untouched,
+/// unless [`WsConfig::max_redirects`] was set.
+pub(crate) async fn follow_redirects<B, F, Fut>(
+    mut request: Request<()>,
+    config: &WsConfig,
+    mut send_handshake: F,
+) -> Result<Response<B>, WsError>
+where
+    F: FnMut(&Request<()>) -> Fut,
+    Fut: std::future::Future<Output = Result<Response<B>, WsError>>,
+{
+    let Some(max_redirects) = config.max_redirects else {
+        return send_handshake(&request).await;
+    };
+
+    let mut hops = 0;
+    loop {
+        let response = send_handshake(&request).await?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+        if hops >= max_redirects {
+            return Err(WsError::TooManyRedirects);
+        }
+        hops += 1;
+
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(WsError::TooManyRedirects)?;
+        let target = resolve_location(request.uri(), location)?;
+
+        let same_origin =
+            request.uri().scheme() == target.scheme() && request.uri().authority() == target.authority();
+
+        *request.uri_mut() = target;
+        if !same_origin {
+            request.headers_mut().remove(header::AUTHORIZATION);
+            request.headers_mut().remove(header::COOKIE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use futures::channel::mpsc as futures_mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn keep_alive_is_noop_without_interval() {
+        let (sink, _rx) = futures_mpsc::unbounded::<Message>();
+        let (_pong_tx, pong_rx) = mpsc::channel(1);
+        assert!(keep_alive(sink, pong_rx, WsConfig::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn keep_alive_times_out_without_a_pong() {
+        let (sink, mut rx) = futures_mpsc::unbounded::<Message>();
+        let (_pong_tx, pong_rx) = mpsc::channel(1);
+        let config = WsConfig::default()
+            .keep_alive_interval(Duration::from_millis(5))
+            .idle_timeout(Duration::from_millis(20));
+
+        let result = keep_alive(sink, pong_rx, config).await;
+        assert!(matches!(result, Err(WsError::KeepAliveTimeout)));
+        assert!(matches!(rx.next().await, Some(Message::Ping(_))));
+    }
+
+    #[tokio::test]
+    async fn keep_alive_ignores_a_stale_pong() {
+        let (sink, _rx) = futures_mpsc::unbounded::<Message>();
+        let (pong_tx, pong_rx) = mpsc::channel(4);
+        // Left over from an earlier round; doesn't match the nonce this round will send.
+        pong_tx.send(b"stale".to_vec()).await.unwrap();
+        let config = WsConfig::default()
+            .keep_alive_interval(Duration::from_millis(5))
+            .idle_timeout(Duration::from_millis(20));
+
+        let result = keep_alive(sink, pong_rx, config).await;
+        assert!(matches!(result, Err(WsError::KeepAliveTimeout)));
+    }
+
+    #[tokio::test]
+    async fn keep_alive_accepts_a_matching_pong() {
+        let (sink, mut rx) = futures_mpsc::unbounded::<Message>();
+        let (pong_tx, pong_rx) = mpsc::channel(4);
+        let config = WsConfig::default()
+            .keep_alive_interval(Duration::from_millis(5))
+            .idle_timeout(Duration::from_millis(50));
+
+        let handle = tokio::spawn(keep_alive(sink, pong_rx, config));
+
+        let Some(Message::Ping(payload)) = rx.next().await else {
+            panic!("expected a Ping frame");
+        };
+        pong_tx.send(payload).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!handle.is_finished(), "a matching Pong should avoid a keepalive timeout");
+        handle.abort();
+    }
+
+    fn redirect_to(location: &'static str) -> Response<()> {
+        Response::builder()
+            .status(http::StatusCode::FOUND)
+            .header(header::LOCATION, location)
+            .body(())
+            .unwrap()
+    }
+
+    fn ok() -> Response<()> {
+        Response::builder().status(http::StatusCode::OK).body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_disabled_returns_response_untouched() {
+        let config = WsConfig::default();
+        let request = Request::get("https://a.example/connect").body(()).unwrap();
+        let calls = Cell::new(0);
+
+        let response = follow_redirects(request, &config, |_req| {
+            calls.set(calls.get() + 1);
+            async { Ok(redirect_to("https://b.example/connect")) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(calls.get(), 1);
+        assert_eq!(response.status(), http::StatusCode::FOUND);
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_exceeding_hop_limit_errors() {
+        let config = WsConfig::default().max_redirects(1);
+        let request = Request::get("https://a.example/connect").body(()).unwrap();
+
+        let result = follow_redirects(request, &config, |_req| async {
+            Ok(redirect_to("https://a.example/connect2"))
+        })
+        .await;
+
+        assert!(matches!(result, Err(WsError::TooManyRedirects)));
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_preserves_auth_header_same_origin() {
+        let config = WsConfig::default().max_redirects(3);
+        let request = Request::get("https://a.example/connect")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(())
+            .unwrap();
+        let hop = Cell::new(0u8);
+
+        let response = follow_redirects(request, &config, |req| {
+            let has_auth = req.headers().contains_key(header::AUTHORIZATION);
+            let this_hop = hop.get();
+            hop.set(this_hop + 1);
+            async move {
+                if this_hop == 0 {
+                    assert!(has_auth, "initial request should carry Authorization");
+                    // Relative target: still same-origin as the request it redirected from.
+                    Ok(redirect_to("/connect2"))
+                } else {
+                    assert!(has_auth, "same-origin redirect should keep Authorization");
+                    Ok(ok())
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_strips_auth_header_cross_origin() {
+        let config = WsConfig::default().max_redirects(3);
+        let request = Request::get("https://a.example/connect")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(())
+            .unwrap();
+        let hop = Cell::new(0u8);
+
+        let response = follow_redirects(request, &config, |req| {
+            let has_auth = req.headers().contains_key(header::AUTHORIZATION);
+            let this_hop = hop.get();
+            hop.set(this_hop + 1);
+            async move {
+                if this_hop == 0 {
+                    assert!(has_auth, "initial request should carry Authorization");
+                    Ok(redirect_to("https://evil.example/connect"))
+                } else {
+                    assert!(!has_auth, "cross-origin redirect should drop Authorization");
+                    Ok(ok())
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+}
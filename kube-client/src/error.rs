@@ -69,44 +69,222 @@ pub enum Error {
     #[error("OpensslError: {0}")]
     OpensslError(#[source] openssl::error::ErrorStack),
 
+    /// WebSocket handshake or connection error
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    #[error("WsError: {0}")]
+    Ws(#[source] WsError),
+
+    /// Errors related to client auth
+    #[cfg(feature = "client")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    #[error("auth error: {0}")]
+    Auth(#[source] crate::client::AuthError),
+}
+
+/// Possible errors when establishing or driving a WebSocket connection
+///
+/// Covers both handshake failures (the connection never upgraded) and
+/// failures that occur once the socket is established.
+#[cfg(feature = "ws")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+#[derive(Error, Debug)]
+pub enum WsError {
     /// The server did not respond with [`SWITCHING_PROTOCOLS`] status when upgrading the
-    /// connection.
+    /// connection over HTTP/1.1.
     ///
     /// [`SWITCHING_PROTOCOLS`]: http::status::StatusCode::SWITCHING_PROTOCOLS
-    #[cfg(feature = "ws")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
     #[error("Failed to switch protocol. Status code: {0}")]
     ProtocolSwitch(http::status::StatusCode),
 
+    /// The server did not respond with [`OK`] status when upgrading the connection over
+    /// HTTP/2 via an Extended CONNECT request.
+    ///
+    /// Unlike the HTTP/1.1 handshake, a successful HTTP/2 upgrade is signalled by `200 OK`
+    /// rather than `101 Switching Protocols`, since the stream itself is repurposed instead
+    /// of the connection.
+    ///
+    /// [`OK`]: http::status::StatusCode::OK
+    #[error("Failed to switch protocol over HTTP/2. Status code: {0}")]
+    Http2ProtocolSwitch(http::status::StatusCode),
+
+    /// The server negotiated HTTP/2 but did not advertise support for Extended CONNECT
+    /// (RFC 8441), so a WebSocket upgrade cannot be attempted on this connection.
+    #[error("Server does not support HTTP/2 Extended CONNECT")]
+    ExtendedConnectNotSupported,
+
+    /// The underlying HTTP/2 connection failed while negotiating an Extended CONNECT stream.
+    #[error("HTTP/2 error during WebSocket handshake: {0}")]
+    Http2(#[source] h2::Error),
+
     /// `Upgrade` header was not set to `websocket` (case insensitive)
-    #[cfg(feature = "ws")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
     #[error("Upgrade header was not set to websocket")]
     MissingUpgradeWebSocketHeader,
 
     /// `Connection` header was not set to `Upgrade` (case insensitive)
-    #[cfg(feature = "ws")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
     #[error("Connection header was not set to Upgrade")]
     MissingConnectionUpgradeHeader,
 
     /// `Sec-WebSocket-Accept` key mismatched.
-    #[cfg(feature = "ws")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
     #[error("Sec-WebSocket-Accept key mismatched")]
     SecWebSocketAcceptKeyMismatch,
 
     /// `Sec-WebSocket-Protocol` mismatched.
-    #[cfg(feature = "ws")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
     #[error("Sec-WebSocket-Protocol mismatched")]
     SecWebSocketProtocolMismatch,
 
-    /// Errors related to client auth
-    #[cfg(feature = "client")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
-    #[error("auth error: {0}")]
-    Auth(#[source] crate::client::AuthError),
+    /// A frame violated the WebSocket protocol, e.g. an invalid opcode or a malformed
+    /// control frame.
+    #[error("WebSocket protocol error: {0}")]
+    ProtocolError(#[source] tokio_tungstenite::tungstenite::error::ProtocolError),
+
+    /// A frame, or the buffer needed to assemble it, exceeded the configured capacity.
+    #[error("WebSocket capacity error: {0}")]
+    Capacity(#[source] tokio_tungstenite::tungstenite::error::CapacityError),
+
+    /// The connection failed while it was in use, e.g. an I/O error on the underlying
+    /// transport or a failure to send a keepalive Ping.
+    ///
+    /// A peer that ends the connection gracefully is not reported as this error.
+    #[error("WebSocket connection closed")]
+    ConnectionClosed,
+
+    /// An operation was attempted on a connection that was already closed.
+    #[error("WebSocket connection already closed")]
+    AlreadyClosed,
+
+    /// No Pong was received from the peer within the configured idle timeout, so the
+    /// connection was considered dead and dropped.
+    #[error("WebSocket connection timed out waiting for a keepalive Pong")]
+    KeepAliveTimeout,
+
+    /// The handshake was redirected more times than the configured hop limit allows.
+    ///
+    /// Also returned when a redirect response has no `Location` header, or one that cannot
+    /// be parsed as a URI.
+    #[error("Too many redirects during WebSocket handshake")]
+    TooManyRedirects,
+}
+
+#[cfg(feature = "ws")]
+impl From<WsError> for Error {
+    fn from(err: WsError) -> Self {
+        Error::Ws(err)
+    }
+}
+
+/// Status code used to indicate why a WebSocket connection is being closed
+///
+/// See [RFC 6455 §7.4](https://datatracker.ietf.org/doc/html/rfc6455#section-7.4) for the
+/// meaning of each numeric range.
+#[cfg(feature = "ws")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CloseCode {
+    /// Normal closure, meaning the purpose for which the connection was established has been
+    /// fulfilled.
+    Normal,
+    /// The endpoint is going away, e.g. server shutdown or a browser tab navigating away.
+    GoingAway,
+    /// The endpoint is terminating the connection due to a protocol error.
+    ProtocolError,
+    /// The endpoint received data of a type it cannot accept.
+    Unsupported,
+    /// Reserved; indicates that no status code was present, and must not be sent over the wire.
+    Status,
+    /// Reserved; indicates the connection was closed abnormally, e.g. without a close frame.
+    Abnormal,
+    /// The endpoint received data that was not consistent with its type, e.g. invalid UTF-8.
+    Invalid,
+    /// The endpoint is terminating the connection because it received a message that violates
+    /// its policy.
+    Policy,
+    /// The endpoint is terminating the connection because a message was too large to process.
+    Size,
+    /// The client expected the server to negotiate an extension it did not.
+    Extension,
+    /// The server encountered an unexpected condition that prevented it from fulfilling the
+    /// request.
+    Error,
+    /// The server is restarting.
+    Restart,
+    /// The server is overloaded and the client should try again later.
+    Again,
+    /// Reserved; indicates the connection was closed due to a failure to perform a TLS
+    /// handshake.
+    Tls,
+    /// A code in the `3000..=3999` range, reserved for use by libraries, frameworks, and
+    /// applications registered with IANA.
+    Library(u16),
+    /// A code in the `4000..=4999` range, available for private use between applications.
+    Bad(u16),
+    /// A code outside the ranges recognized above.
+    Other(u16),
+}
+
+#[cfg(feature = "ws")]
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::Unsupported,
+            1005 => CloseCode::Status,
+            1006 => CloseCode::Abnormal,
+            1007 => CloseCode::Invalid,
+            1008 => CloseCode::Policy,
+            1009 => CloseCode::Size,
+            1010 => CloseCode::Extension,
+            1011 => CloseCode::Error,
+            1012 => CloseCode::Restart,
+            1013 => CloseCode::Again,
+            1015 => CloseCode::Tls,
+            3000..=3999 => CloseCode::Library(code),
+            4000..=4999 => CloseCode::Bad(code),
+            other => CloseCode::Other(other),
+        }
+    }
+}
+
+#[cfg(feature = "ws")]
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::Unsupported => 1003,
+            CloseCode::Status => 1005,
+            CloseCode::Abnormal => 1006,
+            CloseCode::Invalid => 1007,
+            CloseCode::Policy => 1008,
+            CloseCode::Size => 1009,
+            CloseCode::Extension => 1010,
+            CloseCode::Error => 1011,
+            CloseCode::Restart => 1012,
+            CloseCode::Again => 1013,
+            CloseCode::Tls => 1015,
+            CloseCode::Library(code) | CloseCode::Bad(code) | CloseCode::Other(code) => code,
+        }
+    }
+}
+
+/// The code and optional human-readable description sent in a WebSocket close frame
+///
+/// [`next_event`](crate::client::ws::next_event) yields a `CloseReason` built from the peer's
+/// close frame when the exec/attach/port-forward WebSocket stream ends gracefully, instead of
+/// mapping the close to an [`Error`]. Callers distinguish a clean pod exit from a
+/// protocol-level failure this way, and can pass a [`CloseCode`] of their own choosing to
+/// [`send_close`](crate::client::ws::send_close) to end a session deliberately.
+#[cfg(feature = "ws")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CloseReason {
+    /// The close code sent by the peer.
+    pub code: CloseCode,
+    /// An optional, human-readable explanation for why the connection was closed.
+    pub description: Option<String>,
 }
 
 #[derive(Error, Debug)]
@@ -125,3 +303,43 @@ pub enum DiscoveryError {
     #[error("Empty Api Group: {0}")]
     EmptyApiGroup(String),
 }
+
+#[cfg(all(test, feature = "ws"))]
+mod tests {
+    use super::CloseCode;
+
+    #[test]
+    fn close_code_round_trips_named_variants() {
+        for code in 1000..=1003u16 {
+            assert_eq!(u16::from(CloseCode::from(code)), code);
+        }
+        for code in [1005, 1006, 1007, 1008, 1009, 1010, 1011, 1012, 1013, 1015] {
+            assert_eq!(u16::from(CloseCode::from(code)), code);
+        }
+    }
+
+    #[test]
+    fn close_code_boundary_values() {
+        assert_eq!(CloseCode::from(999), CloseCode::Other(999));
+        assert_eq!(CloseCode::from(1000), CloseCode::Normal);
+
+        assert_eq!(CloseCode::from(2999), CloseCode::Other(2999));
+        assert_eq!(CloseCode::from(3000), CloseCode::Library(3000));
+
+        assert_eq!(CloseCode::from(3999), CloseCode::Library(3999));
+        assert_eq!(CloseCode::from(4000), CloseCode::Bad(4000));
+
+        assert_eq!(CloseCode::from(4999), CloseCode::Bad(4999));
+        assert_eq!(CloseCode::from(5000), CloseCode::Other(5000));
+    }
+
+    #[test]
+    fn close_code_range_variants_round_trip() {
+        assert_eq!(u16::from(CloseCode::from(3000)), 3000);
+        assert_eq!(u16::from(CloseCode::from(3999)), 3999);
+        assert_eq!(u16::from(CloseCode::from(4000)), 4000);
+        assert_eq!(u16::from(CloseCode::from(4999)), 4999);
+        assert_eq!(u16::from(CloseCode::from(999)), 999);
+        assert_eq!(u16::from(CloseCode::from(5000)), 5000);
+    }
+}